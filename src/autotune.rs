@@ -0,0 +1,106 @@
+//! Relay-feedback (Åström–Hägglund) autotuning of PID gains.
+//!
+//! Hand-tuning step sizes and PID gains is tedious and doesn't transfer
+//! well between operating points. This module runs a relay-feedback
+//! experiment against a `Simulation` to force a sustained limit cycle, then
+//! derives Ziegler-Nichols gains from the resulting oscillation.
+
+use controller::Parameters;
+use simulation::{f, Flt, Simulation};
+
+use errors::*;
+
+/// Runs a relay-feedback experiment on `sim`, switching the frequency offset
+/// between `+h` and `-h` (relative to the simulation's starting frequency)
+/// whenever the measured polarization crosses its initial value, until
+/// `min_cycles` stable oscillations have been observed. Returns the
+/// Ziegler-Nichols PID gains derived from the ultimate gain `Ku` and
+/// oscillation period `Tu`.
+///
+/// Errors if no stable oscillation develops within `timeout` seconds.
+pub fn autotune<F: Flt>(mut sim: Simulation<F>,
+                         h: F,
+                         timeout: F,
+                         min_cycles: u32)
+                         -> Result<Parameters<F>> {
+    if min_cycles < 1 {
+        bail!("min_cycles must be at least 1");
+    }
+
+    let base_freq = sim.take_data().frequency;
+    let setpoint = sim.take_data().pn;
+    let t_start = sim.take_data().time;
+
+    let mut high = true;
+    sim.set_freq(base_freq + h);
+
+    // Timestamps of each relay switch (i.e. each setpoint crossing), and the
+    // (time, pn) history used to measure the amplitude once we know which
+    // window of crossings is stable.
+    let mut crossings: Vec<F> = Vec::new();
+    let mut history: Vec<(F, F)> = Vec::new();
+
+    loop {
+        for _ in sim.run_for(F::one()) {}
+        let data = sim.take_data();
+
+        if data.time - t_start > timeout {
+            bail!("no stable oscillation developed within {} s",
+                  timeout.to_f64().unwrap_or(0.0));
+        }
+
+        history.push((data.time, data.pn));
+
+        if high && data.pn > setpoint {
+            high = false;
+            sim.set_freq(base_freq - h);
+            crossings.push(data.time);
+        } else if !high && data.pn < setpoint {
+            high = true;
+            sim.set_freq(base_freq + h);
+            crossings.push(data.time);
+        }
+
+        // Two crossings make a half-period; keep going until we have
+        // `min_cycles` full periods (2 * min_cycles crossing-to-crossing
+        // intervals) past the initial transient.
+        if crossings.len() as u32 >= 2 * min_cycles + 1 {
+            break;
+        }
+    }
+
+    // Keep exactly `2 * min_cycles + 1` crossings, i.e. `2 * min_cycles`
+    // crossing-to-crossing intervals (half-periods), discarding the initial
+    // transient that came before them.
+    let stable = &crossings[crossings.len() - (2 * min_cycles as usize + 1)..];
+    let tu = (stable[stable.len() - 1] - stable[0]) / f(min_cycles as f64);
+
+    // Measure the amplitude only over the same stable window used for `Tu`,
+    // since the discarded transient can swing wider or narrower than the
+    // settled limit cycle.
+    let stable_start = stable[0];
+    let mut pn_min = F::max_value();
+    let mut pn_max = F::min_value();
+    for &(t, pn) in &history {
+        if t >= stable_start {
+            if pn < pn_min {
+                pn_min = pn;
+            }
+            if pn > pn_max {
+                pn_max = pn;
+            }
+        }
+    }
+    let a = pn_max - pn_min;
+
+    let ku = f::<F>(4.0) * h / (F::PI() * a);
+
+    Ok(Parameters {
+        kp: f::<F>(0.6) * ku,
+        ki: f::<F>(1.2) * ku / tu,
+        kd: f::<F>(0.075) * ku * tu,
+        output_min: -h,
+        output_max: h,
+        target: F::zero(),
+    })
+}
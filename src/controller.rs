@@ -5,17 +5,17 @@ use std::marker::PhantomData;
 
 use rand;
 
-use simulation::{Simulation, SimData};
+use simulation::{f, Flt, SimData, Simulation};
 
-pub trait Controller: Sized {
+pub trait Controller<F: Flt>: Sized {
     /// Creates a new controller for the given simulation
-    fn from_sim(sim: Simulation) -> Self;
+    fn from_sim(sim: Simulation<F>) -> Self;
     /// Returns the controller data at the current time.
-    fn take_data(&self) -> ControllerData;
+    fn take_data(&self) -> ControllerData<F>;
     /// Makes a single step in the algorithm.
-    fn control_step(&mut self) -> ControllerData;
+    fn control_step(&mut self) -> ControllerData<F>;
 
-    fn control_until(&mut self, t_final: f64) -> ControlUntil<Self> {
+    fn control_until(&mut self, t_final: F) -> ControlUntil<F, Self> {
         ControlUntil {
             controller: self,
             t_final: t_final,
@@ -23,24 +23,26 @@ pub trait Controller: Sized {
     }
 }
 
-pub struct ControlUntil<'a, T>
-    where T: 'a + Controller
+pub struct ControlUntil<'a, F, T>
+    where F: Flt,
+          T: 'a + Controller<F>
 {
     controller: &'a mut T,
-    t_final: f64,
+    t_final: F,
 }
 
-pub struct ControllerData {
-    pub sim_data: SimData,
-    pub rate: f64,
+pub struct ControllerData<F: Flt> {
+    pub sim_data: SimData<F>,
+    pub rate: F,
 }
 
-impl<'a, T> Iterator for ControlUntil<'a, T>
-    where T: 'a + Controller
+impl<'a, F, T> Iterator for ControlUntil<'a, F, T>
+    where F: Flt,
+          T: 'a + Controller<F>
 {
-    type Item = ControllerData;
+    type Item = ControllerData<F>;
 
-    fn next(&mut self) -> Option<ControllerData> {
+    fn next(&mut self) -> Option<ControllerData<F>> {
         if self.controller.take_data().sim_data.time > self.t_final {
             None
         } else {
@@ -50,51 +52,52 @@ impl<'a, T> Iterator for ControlUntil<'a, T>
 }
 
 /// This seems like an abuse of the type system but whatever
-pub trait ThreePointControllerFn {
+pub trait ThreePointControllerFn<F: Flt> {
     /// Calculate the rate from three data points.
-    fn calc_rate(d1: &SimData, d2: &SimData, d3: &SimData) -> f64;
+    fn calc_rate(d1: &SimData<F>, d2: &SimData<F>, d3: &SimData<F>) -> F;
 }
 
 /// A generic controller that runs based on a rate calculated from three
 /// data points (the "rate" doesn't actually have to be a rate, but it
 /// will be compared in the same way).
-pub struct ThreePointController<T: ThreePointControllerFn> {
-    sim: Simulation,
-    last_rate: f64,
+pub struct ThreePointController<F: Flt, T: ThreePointControllerFn<F>> {
+    sim: Simulation<F>,
+    last_rate: F,
     // Move up = `true`
     direction: bool,
-    step_size: f64,
+    step_size: F,
     /// This is definitely a type system hack
     wat: PhantomData<T>,
 }
 
-impl<T> Controller for ThreePointController<T>
-    where T: ThreePointControllerFn
+impl<F, T> Controller<F> for ThreePointController<F, T>
+    where F: Flt,
+          T: ThreePointControllerFn<F>
 {
-    fn from_sim(sim: Simulation) -> ThreePointController<T> {
+    fn from_sim(sim: Simulation<F>) -> ThreePointController<F, T> {
         ThreePointController {
             sim: sim,
-            last_rate: 0.0,
+            last_rate: F::zero(),
             direction: true,
-            step_size: 0.03,
+            step_size: f(0.03),
             wat: PhantomData,
         }
     }
 
-    fn take_data(&self) -> ControllerData {
+    fn take_data(&self) -> ControllerData<F> {
         ControllerData {
             sim_data: self.sim.take_data(),
             rate: self.last_rate,
         }
     }
 
-    fn control_step(&mut self) -> ControllerData {
+    fn control_step(&mut self) -> ControllerData<F> {
         // Here is where we should implement the controller algorithm
         let sim = &mut self.sim;
         let d1 = sim.take_data();
-        for _ in sim.run_for(1.0) {}
+        for _ in sim.run_for(F::one()) {}
         let d2 = sim.take_data();
-        for _ in sim.run_for(1.0) {}
+        for _ in sim.run_for(F::one()) {}
         let d3 = sim.take_data();
 
         // Calculate rate
@@ -103,10 +106,10 @@ impl<T> Controller for ThreePointController<T>
         if rate < self.last_rate {
             // Switch directions and decrease step size
             self.direction = !self.direction;
-            self.step_size *= 0.8;
+            self.step_size = self.step_size * f(0.8);
             // Make sure it doesn't get too low
-            if self.step_size < 0.001 {
-                self.step_size = 0.001;
+            if self.step_size < f(0.001) {
+                self.step_size = f(0.001);
             }
         }
 
@@ -122,7 +125,7 @@ impl<T> Controller for ThreePointController<T>
         sim.set_freq(d3.frequency + step);
 
         // Give it time to settle
-        for _ in sim.run_for(5.0) {}
+        for _ in sim.run_for(f(5.0)) {}
 
         ControllerData {
             sim_data: d3,
@@ -134,36 +137,36 @@ impl<T> Controller for ThreePointController<T>
 /// The standard controller algorithm
 pub struct StdControllerFn;
 
-impl ThreePointControllerFn for StdControllerFn {
-    fn calc_rate(d1: &SimData, d2: &SimData, d3: &SimData) -> f64 {
-        let p1 = (d2.pn + d1.pn) / 2.0;
-        let p2 = (d3.pn + d2.pn) / 2.0;
-        let e1 = (d2.time + d1.time) / 2.0;
-        let e2 = (d3.time + d2.time) / 2.0;
+impl<F: Flt> ThreePointControllerFn<F> for StdControllerFn {
+    fn calc_rate(d1: &SimData<F>, d2: &SimData<F>, d3: &SimData<F>) -> F {
+        let p1 = (d2.pn + d1.pn) / f(2.0);
+        let p2 = (d3.pn + d2.pn) / f(2.0);
+        let e1 = (d2.time + d1.time) / f(2.0);
+        let e2 = (d3.time + d2.time) / f(2.0);
         (p2 - p1) / (e2 - e1)
     }
 }
 
-pub type StdController = ThreePointController<StdControllerFn>;
+pub type StdController<F> = ThreePointController<F, StdControllerFn>;
 
 /// The standard controller algorithm (variant)
 pub struct StdControllerFn2;
 
-impl ThreePointControllerFn for StdControllerFn2 {
-    fn calc_rate(d1: &SimData, d2: &SimData, d3: &SimData) -> f64 {
+impl<F: Flt> ThreePointControllerFn<F> for StdControllerFn2 {
+    fn calc_rate(d1: &SimData<F>, d2: &SimData<F>, d3: &SimData<F>) -> F {
         let r1 = (d2.pn - d1.pn) / (d2.time - d1.time);
         let r2 = (d3.pn - d2.pn) / (d3.time - d2.time);
-        (r1 + r2) / 2.0
+        (r1 + r2) / f(2.0)
     }
 }
 
-pub type StdController2 = ThreePointController<StdControllerFn2>;
+pub type StdController2<F> = ThreePointController<F, StdControllerFn2>;
 
 /// Test of a "k-val based" controller algorithm
 pub struct KValControllerFn;
 
-impl ThreePointControllerFn for KValControllerFn {
-    fn calc_rate(d1: &SimData, d2: &SimData, d3: &SimData) -> f64 {
+impl<F: Flt> ThreePointControllerFn<F> for KValControllerFn {
+    fn calc_rate(d1: &SimData<F>, d2: &SimData<F>, d3: &SimData<F>) -> F {
         // The "rate" here is actually going to be the steady state value
         // For convenience
         let (x1, y1) = (d1.time, d1.pn);
@@ -181,8 +184,8 @@ impl ThreePointControllerFn for KValControllerFn {
         // Nobody cares what c is
 
         // Try to compute first and second derivatives at x2
-        let first_deriv = 2.0 * a * x2 + b;
-        let second_deriv = 2.0 * a;
+        let first_deriv = f::<F>(2.0) * a * x2 + b;
+        let second_deriv = f::<F>(2.0) * a;
 
         // Now return the steady state
         let ss = y2 - first_deriv * first_deriv / second_deriv;
@@ -190,47 +193,47 @@ impl ThreePointControllerFn for KValControllerFn {
     }
 }
 
-pub type KValController = ThreePointController<KValControllerFn>;
+pub type KValController<F> = ThreePointController<F, KValControllerFn>;
 
 /// A random controller (control group)
-pub struct RandController {
-    sim: Simulation,
-    last_rate: f64,
+pub struct RandController<F: Flt> {
+    sim: Simulation<F>,
+    last_rate: F,
     direction: bool,
-    step_size: f64,
+    step_size: F,
 }
 
-impl Controller for RandController {
-    fn from_sim(sim: Simulation) -> RandController {
+impl<F: Flt> Controller<F> for RandController<F> {
+    fn from_sim(sim: Simulation<F>) -> RandController<F> {
         RandController {
             sim: sim,
-            last_rate: 0.0,
+            last_rate: F::zero(),
             direction: true,
-            step_size: 0.03,
+            step_size: f(0.03),
         }
     }
 
-    fn take_data(&self) -> ControllerData {
+    fn take_data(&self) -> ControllerData<F> {
         ControllerData {
             sim_data: self.sim.take_data(),
             rate: self.last_rate,
         }
     }
 
-    fn control_step(&mut self) -> ControllerData {
+    fn control_step(&mut self) -> ControllerData<F> {
         // Here is where we should implement the controller algorithm
         let sim = &mut self.sim;
-        for _ in sim.run_for(1.0) {}
-        for _ in sim.run_for(1.0) {}
+        for _ in sim.run_for(F::one()) {}
+        for _ in sim.run_for(F::one()) {}
         let d3 = sim.take_data();
 
         if rand::random() {
             // Switch directions and decrease step size
             self.direction = !self.direction;
-            self.step_size *= 0.8;
+            self.step_size = self.step_size * f(0.8);
             // Make sure it doesn't get too low
-            if self.step_size < 0.001 {
-                self.step_size = 0.001;
+            if self.step_size < f(0.001) {
+                self.step_size = f(0.001);
             }
         }
 
@@ -243,11 +246,252 @@ impl Controller for RandController {
         sim.set_freq(d3.frequency + step);
 
         // Give it time to settle
-        for _ in sim.run_for(5.0) {}
+        for _ in sim.run_for(f(5.0)) {}
 
         ControllerData {
             sim_data: d3,
-            rate: 0.0,
+            rate: F::zero(),
+        }
+    }
+}
+
+/// Gains and output limits for `PidController`.
+#[derive(Clone, Copy)]
+pub struct Parameters<F: Flt> {
+    pub kp: F,
+    pub ki: F,
+    pub kd: F,
+    pub output_min: F,
+    pub output_max: F,
+    /// The rate the controller tries to drive the measured polarization
+    /// rate toward (0.0 corresponds to the maximum-polarization condition).
+    pub target: F,
+}
+
+impl<F: Flt> Default for Parameters<F> {
+    fn default() -> Parameters<F> {
+        Parameters {
+            kp: F::zero(),
+            ki: F::zero(),
+            kd: F::zero(),
+            output_min: f(-1.0),
+            output_max: F::one(),
+            target: F::zero(),
+        }
+    }
+}
+
+/// A velocity-form (incremental) PID controller that drives the measured
+/// polarization rate toward `target` by adjusting the microwave frequency.
+/// The output is clamped to `[output_min, output_max]` on every step; since
+/// no separate integral accumulator is kept, this clamping is itself the
+/// anti-windup mechanism.
+pub struct PidController<F: Flt, T: ThreePointControllerFn<F>> {
+    sim: Simulation<F>,
+    params: Parameters<F>,
+    // The last two rate inputs and the last output, as used by the
+    // incremental PID formula.
+    x1: F,
+    x2: F,
+    y1: F,
+    /// This is definitely a type system hack
+    wat: PhantomData<T>,
+}
+
+impl<F: Flt, T: ThreePointControllerFn<F>> PidController<F, T> {
+    /// Creates a new controller for the given simulation with the given gains.
+    pub fn with_parameters(sim: Simulation<F>, params: Parameters<F>) -> PidController<F, T> {
+        PidController {
+            sim: sim,
+            params: params,
+            x1: F::zero(),
+            x2: F::zero(),
+            y1: F::zero(),
+            wat: PhantomData,
+        }
+    }
+
+    /// Gets the current gains and output limits, so they can be adjusted.
+    pub fn parameters_mut(&mut self) -> &mut Parameters<F> {
+        &mut self.params
+    }
+}
+
+impl<F, T> Controller<F> for PidController<F, T>
+    where F: Flt,
+          T: ThreePointControllerFn<F>
+{
+    fn from_sim(sim: Simulation<F>) -> PidController<F, T> {
+        PidController::with_parameters(sim, Parameters::default())
+    }
+
+    fn take_data(&self) -> ControllerData<F> {
+        ControllerData {
+            sim_data: self.sim.take_data(),
+            rate: self.x1,
+        }
+    }
+
+    fn control_step(&mut self) -> ControllerData<F> {
+        let sim = &mut self.sim;
+        let d1 = sim.take_data();
+        for _ in sim.run_for(F::one()) {}
+        let d2 = sim.take_data();
+        for _ in sim.run_for(F::one()) {}
+        let d3 = sim.take_data();
+
+        // Calculate the current rate
+        let x0 = T::calc_rate(&d1, &d2, &d3);
+
+        let Parameters { kp, ki, kd, target, output_min, output_max } = self.params;
+        let mut y0 = self.y1 - ki * target + x0 * (kp + ki + kd) -
+                     self.x1 * (kp + f::<F>(2.0) * kd) + self.x2 * kd;
+        // Clamp the output; since there's no separate integral accumulator,
+        // this clamping is the anti-windup
+        if y0 < output_min {
+            y0 = output_min;
+        } else if y0 > output_max {
+            y0 = output_max;
+        }
+
+        // Shift the history and move the motor
+        self.x2 = self.x1;
+        self.x1 = x0;
+        self.y1 = y0;
+        sim.set_freq(d3.frequency + y0);
+
+        // Give it time to settle
+        for _ in sim.run_for(f(5.0)) {}
+
+        ControllerData {
+            sim_data: d3,
+            rate: x0,
+        }
+    }
+}
+
+/// Configuration for `SaController`.
+#[derive(Clone, Copy)]
+pub struct SaParameters<F: Flt> {
+    /// Standard deviation of the Gaussian proposal distribution (GHz).
+    pub proposal_scale: F,
+    /// Starting annealing temperature.
+    pub initial_temp: F,
+    /// Factor the temperature is multiplied by after each step.
+    pub cooling_rate: F,
+}
+
+impl<F: Flt> Default for SaParameters<F> {
+    fn default() -> SaParameters<F> {
+        SaParameters {
+            proposal_scale: f(0.01),
+            initial_temp: F::one(),
+            cooling_rate: f(0.95),
+        }
+    }
+}
+
+/// A simulated-annealing controller that searches frequency space for the
+/// global maximum steady-state polarization, rather than greedily
+/// hill-climbing like the `ThreePointController` family. This lets it escape
+/// the local optima the direction-flipping controllers get stuck in near the
+/// two Gaussian transition peaks.
+pub struct SaController<F: Flt> {
+    sim: Simulation<F>,
+    params: SaParameters<F>,
+    freq: F,
+    /// Energy of the current frequency (the negative of the polarization
+    /// read after the settling run; lower energy is better).
+    energy: F,
+    f_best: F,
+    p_best: F,
+    temp: F,
+}
+
+impl<F: Flt> SaController<F> {
+    /// Creates a new controller for the given simulation with the given
+    /// annealing schedule.
+    pub fn with_parameters(sim: Simulation<F>, params: SaParameters<F>) -> SaController<F> {
+        let temp = params.initial_temp;
+        let data = sim.take_data();
+        let freq = data.frequency;
+        SaController {
+            sim: sim,
+            params: params,
+            freq: freq,
+            energy: -data.pn,
+            f_best: freq,
+            p_best: data.pn,
+            temp: temp,
+        }
+    }
+
+    /// The best frequency found so far.
+    pub fn f_best(&self) -> F {
+        self.f_best
+    }
+
+    /// The polarization at `f_best`.
+    pub fn p_best(&self) -> F {
+        self.p_best
+    }
+
+    /// Samples a proposal offset from a Gaussian distribution with the
+    /// configured standard deviation, using the Box-Muller transform.
+    fn propose(&self) -> F {
+        // Sample u1 from (0, 1] rather than [0, 1) so `u1.ln()` never blows
+        // up at u1 == 0.
+        let u1: F = f(1.0 - rand::random::<f64>());
+        let u2: F = f(rand::random::<f64>());
+        self.freq + self.params.proposal_scale * (-f::<F>(2.0) * u1.ln()).sqrt() *
+        (f::<F>(2.0) * F::PI() * u2).cos()
+    }
+}
+
+impl<F: Flt> Controller<F> for SaController<F> {
+    fn from_sim(sim: Simulation<F>) -> SaController<F> {
+        SaController::with_parameters(sim, SaParameters::default())
+    }
+
+    fn take_data(&self) -> ControllerData<F> {
+        ControllerData {
+            sim_data: self.sim.take_data(),
+            rate: -self.energy,
+        }
+    }
+
+    fn control_step(&mut self) -> ControllerData<F> {
+        let f_prev = self.freq;
+        let f_new = self.propose();
+
+        let sim = &mut self.sim;
+        sim.set_freq(f_new);
+        for _ in sim.run_for(f(5.0)) {}
+        let data = sim.take_data();
+        let energy_new = -data.pn;
+
+        let accepted = {
+            let de = energy_new - self.energy;
+            de < F::zero() || f::<F>(rand::random::<f64>()) < (-de / self.temp).exp()
+        };
+
+        if accepted {
+            self.freq = f_new;
+            self.energy = energy_new;
+            if data.pn > self.p_best {
+                self.p_best = data.pn;
+                self.f_best = f_new;
+            }
+        } else {
+            // Reject the proposal and restore the previous frequency
+            sim.set_freq(f_prev);
+        }
+
+        self.temp = self.temp * self.params.cooling_rate;
+
+        ControllerData {
+            sim_data: data,
+            rate: -self.energy,
         }
     }
 }
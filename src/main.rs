@@ -1,20 +1,28 @@
 #[macro_use]
 extern crate error_chain;
+extern crate num_traits;
 extern crate rand;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
 mod errors {
     error_chain!{}
 }
 use errors::*;
 
+mod autotune;
+mod controller;
 mod pdp;
+mod scenario;
 mod simulation;
 
 use std::fs::File;
 use std::io::{self, Write};
 
 use pdp::Pdp;
-use simulation::Builder;
+use simulation::SimBuilder;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -46,10 +54,12 @@ fn run() -> Result<bool> {
     match prompt("Choose operation:")?.as_str() {
         "pdp" => pdp()?,
         "sim" => sim()?,
+        "scenario" => scenario()?,
         "help" => {
             println!("Available commands are:
 ---- pdp: Simulate the PDP environment
----- sim: Run the simulation without sweeping")
+---- sim: Run the simulation without sweeping
+---- scenario: Run a scripted scenario from a JSON file")
         }
         "quit" => return Ok(true),
         s => {
@@ -80,7 +90,7 @@ fn pdp() -> Result<()> {
         .parse::<u32>()
         .chain_err(|| "invalid number of sweeps")?;
 
-    let mut pdp = Pdp::new(Builder::new(freq).build(), n_sweeps);
+    let mut pdp = Pdp::new(SimBuilder::new(freq).build(), n_sweeps);
     for data in pdp.run_for_iter(time) {
         writeln!(output, "{}", data.to_csv())
             .chain_err(|| "could not write to output file")?;
@@ -105,8 +115,8 @@ fn sim() -> Result<()> {
         .parse::<f64>()
         .chain_err(|| "invalid time")?;
 
-    let mut sim = Builder::new(freq).build();
-    for data in sim.run_for_iter(time, 0.001, 1.0) {
+    let mut sim = SimBuilder::new(freq).build();
+    for data in sim.run_for(time) {
         writeln!(output, "{}", data.to_csv())
             .chain_err(|| "could not write to output file")?;
     }
@@ -114,6 +124,28 @@ fn sim() -> Result<()> {
     Ok(())
 }
 
+fn scenario() -> Result<()> {
+    let path = prompt("Scenario file (JSON):")?;
+    let file = File::open(&path)
+        .chain_err(|| format!("could not open scenario file `{}`", path))?;
+    let scenario: scenario::Scenario = serde_json::from_reader(file)
+        .chain_err(|| "could not parse scenario file")?;
+
+    let base = prompt("Output file base name (leave blank for stdout):")?;
+    let (mut csv_out, mut json_out): (Box<Write>, Box<Write>) = if base.is_empty() {
+        (Box::new(io::stdout()), Box::new(io::stdout()))
+    } else {
+        let csv_path = format!("{}.csv", base);
+        let json_path = format!("{}.jsonl", base);
+        (Box::new(File::create(&csv_path)
+                      .chain_err(|| format!("could not create output file `{}`", csv_path))?),
+         Box::new(File::create(&json_path)
+                      .chain_err(|| format!("could not create output file `{}`", json_path))?))
+    };
+
+    scenario::run(&scenario, &mut *csv_out, &mut *json_out)
+}
+
 fn prompt(title: &str) -> Result<String> {
     print!("{} ", title);
     io::stdout()
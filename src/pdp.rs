@@ -2,7 +2,7 @@
 
 use rand;
 
-use simulation::{Data, Simulation};
+use simulation::{SimData, Simulation};
 
 const MS_PER_SWEEP: f64 = 64.0;
 /// The fractional error in polarization per sweep.
@@ -11,7 +11,7 @@ const SWEEP_UNCERTAINTY: f64 = 0.04;
 /// The state of the PDP simulation.
 pub struct Pdp {
     /// The underlying system.
-    sim: Simulation,
+    sim: Simulation<f64>,
     /// The number of sweeps per data reading.
     n_sweeps: u32,
 }
@@ -24,12 +24,12 @@ pub struct RunUntil<'a> {
 
 impl Pdp {
     /// Create a new PDP simulator with the given underlying `Simulation` and number of sweeps.
-    pub fn new(sim: Simulation, n_sweeps: u32) -> Self {
+    pub fn new(sim: Simulation<f64>, n_sweeps: u32) -> Self {
         Pdp { sim, n_sweeps }
     }
 
     /// Gets a single data point by sweeping.
-    pub fn take_data(&mut self) -> Data {
+    pub fn take_data(&mut self) -> SimData<f64> {
         // Collect polarization for averaging
         let mut pn = 0.0;
         for _ in 0..self.n_sweeps {
@@ -54,8 +54,8 @@ impl Pdp {
     }
 
     /// Perform a single sweep and return its data.
-    fn sweep(&mut self) -> Data {
-        self.sim.run_for(MS_PER_SWEEP / 1000.0, 0.001);
+    fn sweep(&mut self) -> SimData<f64> {
+        for _ in self.sim.run_for(MS_PER_SWEEP / 1000.0) {}
         let mut sweep_data = self.sim.take_data();
         // Fuzz polarization
         sweep_data.pn += SWEEP_UNCERTAINTY * (rand::random::<f64>() - 0.5);
@@ -64,9 +64,9 @@ impl Pdp {
 }
 
 impl<'a> Iterator for RunUntil<'a> {
-    type Item = Data;
+    type Item = SimData<f64>;
 
-    fn next(&mut self) -> Option<Data> {
+    fn next(&mut self) -> Option<SimData<f64>> {
         if self.pdp.sim.take_data().time < self.t_final {
             Some(self.pdp.take_data())
         } else {
@@ -0,0 +1,99 @@
+//! Serde-driven experiment scenarios: a timeline of timestamped events
+//! applied to a `Simulation`, as an alternative to the single
+//! frequency/duration prompts in `main`'s `pdp()`/`sim()` flows.
+
+use std::io::Write;
+
+use serde_json;
+
+use simulation::{SimBuilder, SimData};
+
+use errors::*;
+
+/// A single timestamped change to apply to the simulation.
+#[derive(Clone, Deserialize)]
+pub enum Event {
+    SetFreq { t: f64, ghz: f64 },
+    BeamOn { t: f64, current: f64 },
+    BeamOff { t: f64 },
+    Anneal { t: f64, duration: f64, temperature: f64 },
+    SetSystemTemperature { t: f64, kelvin: f64 },
+}
+
+impl Event {
+    fn time(&self) -> f64 {
+        match *self {
+            Event::SetFreq { t, .. } => t,
+            Event::BeamOn { t, .. } => t,
+            Event::BeamOff { t } => t,
+            Event::Anneal { t, .. } => t,
+            Event::SetSystemTemperature { t, .. } => t,
+        }
+    }
+}
+
+/// An experiment scenario: the initial simulation configuration, the output
+/// sampling interval, and the timeline of events to apply.
+#[derive(Deserialize)]
+pub struct Scenario {
+    pub freq: f64,
+    pub c: f64,
+    pub temperature: f64,
+    pub t1n: f64,
+    pub t1e: f64,
+    /// How often (in simulation seconds) to emit an output row.
+    pub sample_interval: f64,
+    pub events: Vec<Event>,
+}
+
+/// Runs `scenario` to completion, advancing the simulation between events
+/// with `Simulation::run_until` and applying each event at its timestamp.
+/// Writes one row per sampling interval to `csv_out` (as CSV) and to
+/// `json_out` (as line-delimited JSON).
+pub fn run(scenario: &Scenario, csv_out: &mut Write, json_out: &mut Write) -> Result<()> {
+    if scenario.sample_interval <= 0.0 {
+        bail!("sample_interval must be positive");
+    }
+
+    let mut sim = SimBuilder::new(scenario.freq)
+        .c(scenario.c)
+        .temperature(scenario.temperature)
+        .physical_constants(scenario.t1n, scenario.t1e)
+        .build();
+
+    let mut events = scenario.events.clone();
+    events.sort_by(|a, b| a.time().partial_cmp(&b.time()).unwrap());
+
+    write_row(&sim.take_data(), csv_out, json_out)?;
+    let mut t_next_sample = scenario.sample_interval;
+
+    for event in events {
+        let t_event = event.time();
+
+        while t_next_sample < t_event {
+            for _ in sim.run_until(t_next_sample) {}
+            write_row(&sim.take_data(), csv_out, json_out)?;
+            t_next_sample += scenario.sample_interval;
+        }
+        for _ in sim.run_until(t_event) {}
+
+        match event {
+            Event::SetFreq { ghz, .. } => sim.set_freq(ghz),
+            Event::BeamOn { current, .. } => sim.beam_on(current),
+            Event::BeamOff { .. } => sim.beam_off(),
+            Event::Anneal { duration, temperature, .. } => sim.anneal(duration, temperature),
+            Event::SetSystemTemperature { kelvin, .. } => sim.set_system_temperature(kelvin),
+        }
+    }
+
+    Ok(())
+}
+
+fn write_row(data: &SimData<f64>, csv_out: &mut Write, json_out: &mut Write) -> Result<()> {
+    writeln!(csv_out, "{}", data.to_csv()).chain_err(|| "could not write CSV output")?;
+
+    let line = serde_json::to_string(data).chain_err(|| "could not serialize data point")?;
+    writeln!(json_out, "{}", line).chain_err(|| "could not write JSON output")?;
+
+    Ok(())
+}
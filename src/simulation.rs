@@ -2,11 +2,25 @@
 
 #![allow(dead_code)]
 
-use std::f64::consts;
+use std::fmt;
 use std::iter::Iterator;
 
+use num_traits::{Float, FloatConst, FromPrimitive, ToPrimitive};
 use rand;
 
+/// The numeric type `Simulation` and friends are generic over. `f64` is the
+/// default, but callers doing large parameter sweeps can use `f32` instead
+/// for roughly half the memory and faster throughput.
+pub trait Flt: Float + FloatConst + FromPrimitive + ToPrimitive {}
+
+impl<T: Float + FloatConst + FromPrimitive + ToPrimitive> Flt for T {}
+
+/// Converts an `f64` literal to `F`, to keep the numeric literals below
+/// readable.
+pub(crate) fn f<F: Flt>(x: f64) -> F {
+    F::from_f64(x).unwrap()
+}
+
 /// Elementary charge (in C)
 pub static ELEM_CHARGE: f64 = 1.602176662e-19;
 
@@ -48,122 +62,137 @@ static BASE_RANDOMNESS: f64 = 0.002;
 static IRRADIATION_FACTOR: f64 = 1e-10;
 
 /// Represents a solid polarized target experiment.
-pub struct Simulation {
+pub struct Simulation<F: Flt> {
     /// Physical constants
-    t1n: f64,
-    t1e: f64,
+    t1n: F,
+    t1e: F,
 
     /// "External" physical parameters
     /// The system_temperature is the temperature of the system;
     /// in normal situations, this is 1K (the temperature of the fridge)
-    t: f64,
-    freq: f64,
-    temperature: f64,
-    system_temperature: f64,
+    t: F,
+    freq: F,
+    temperature: F,
+    system_temperature: F,
 
     /// Internal physical parameters
-    alpha: f64,
-    beta: f64,
-    c: f64,
-    pe0: f64,
-    phi: f64,
+    alpha: F,
+    beta: F,
+    c: F,
+    pe0: F,
+    phi: F,
 
     /// Polarization values
     /// pn_raw is the "raw polarization" (without noise)
-    pn_raw: f64,
-    pn: f64,
-    pe: f64,
+    pn_raw: F,
+    pn: F,
+    pe: F,
 
     /// Dose
-    dose: f64,
-    beam_current: f64,
+    dose: F,
+    beam_current: F,
 }
 
 /// For building `Simulation`s using the builder pattern
-pub struct SimBuilder {
-    freq: f64,
-    pn: f64,
-    pe: f64,
-    c: f64,
-    temperature: f64,
-    t1n: f64,
-    t1e: f64,
+pub struct SimBuilder<F: Flt> {
+    freq: F,
+    pn: F,
+    pe: F,
+    c: F,
+    temperature: F,
+    t1n: F,
+    t1e: F,
 }
 
 /// A single "data point" containing all observable data at a particular time
-pub struct SimData {
-    pub time: f64,
-    pub pn: f64,
-    pub pe: f64,
-    pub frequency: f64,
-    pub c: f64,
-    pub temperature: f64,
-    pub dose: f64,
+#[derive(Clone, Serialize)]
+pub struct SimData<F: Flt> {
+    pub time: F,
+    pub pn: F,
+    pub pe: F,
+    pub frequency: F,
+    pub c: F,
+    pub temperature: F,
+    pub dose: F,
+}
+
+impl<F: Flt + fmt::Display> SimData<F> {
+    /// Formats this data point as a single CSV row.
+    pub fn to_csv(&self) -> String {
+        format!("{},{},{},{},{},{},{}",
+                self.time,
+                self.pn,
+                self.pe,
+                self.frequency,
+                self.c,
+                self.temperature,
+                self.dose)
+    }
 }
 
 /// An iterator returning data points for each time step
-pub struct RunUntil<'a> {
-    sim: &'a mut Simulation,
-    t_final: f64,
+pub struct RunUntil<'a, F: Flt + 'a> {
+    sim: &'a mut Simulation<F>,
+    t_final: F,
 }
 
-impl SimBuilder {
-    pub fn new(freq: f64) -> SimBuilder {
+impl<F: Flt> SimBuilder<F> {
+    pub fn new(freq: F) -> SimBuilder<F> {
         SimBuilder {
             freq: freq,
-            pn: 0.0,
-            pe: -1.0,
-            c: 0.000136073,
-            temperature: 1.0,
-            t1n: 25.0 * 60.0,
-            t1e: 0.03,
+            pn: F::zero(),
+            pe: f(-1.0),
+            c: f(0.000136073),
+            temperature: F::one(),
+            t1n: f(25.0 * 60.0),
+            t1e: f(0.03),
         }
     }
 
-    pub fn initial_pol(&mut self, t1n: f64, t1e: f64) -> &mut SimBuilder {
+    pub fn initial_pol(&mut self, t1n: F, t1e: F) -> &mut SimBuilder<F> {
         self.t1n = t1n;
         self.t1e = t1e;
         self
     }
 
-    pub fn c(&mut self, c: f64) -> &mut SimBuilder {
+    pub fn c(&mut self, c: F) -> &mut SimBuilder<F> {
         self.c = c;
         self
     }
 
-    pub fn temperature(&mut self, temperature: f64) -> &mut SimBuilder {
+    pub fn temperature(&mut self, temperature: F) -> &mut SimBuilder<F> {
         self.temperature = temperature;
         self
     }
 
-    pub fn physical_constants(&mut self, t1n: f64, t1e: f64) -> &mut SimBuilder {
+    pub fn physical_constants(&mut self, t1n: F, t1e: F) -> &mut SimBuilder<F> {
         self.t1n = t1n;
         self.t1e = t1e;
         self
     }
 
-    pub fn build(&self) -> Simulation {
+    pub fn build(&self) -> Simulation<F> {
         let mut sim = Simulation {
             t1n: self.t1n,
             t1e: self.t1e,
 
-            t: 0.0,
+            t: F::zero(),
             freq: self.freq,
             temperature: self.temperature,
             system_temperature: self.temperature,
 
-            alpha: 0.0,
-            beta: 0.0,
+            alpha: F::zero(),
+            beta: F::zero(),
             c: self.c,
-            pe0: 0.0,
-            phi: 0.0,
+            pe0: F::zero(),
+            phi: F::zero(),
 
             pn_raw: self.pn,
             pn: self.pn,
             pe: self.pe,
 
-            dose: 0.0,
-            beam_current: 0.0,
+            dose: F::zero(),
+            beam_current: F::zero(),
         };
 
         // Make sure we do all the necessary initialization
@@ -175,41 +204,41 @@ impl SimBuilder {
     }
 }
 
-impl Simulation {
-    pub fn set_freq(&mut self, freq: f64) {
+impl<F: Flt> Simulation<F> {
+    pub fn set_freq(&mut self, freq: F) {
         self.freq = freq;
         self.calc_transition_rates();
     }
 
-    pub fn set_system_temperature(&mut self, temperature: f64) {
+    pub fn set_system_temperature(&mut self, temperature: F) {
         self.system_temperature = temperature;
     }
 
-    pub fn beam_on(&mut self, current: f64) {
+    pub fn beam_on(&mut self, current: F) {
         self.beam_current = current;
     }
 
     pub fn beam_off(&mut self) {
-        self.beam_current = 0.0;
+        self.beam_current = F::zero();
     }
 
-    pub fn run_until(&mut self, t_final: f64) -> RunUntil {
+    pub fn run_until(&mut self, t_final: F) -> RunUntil<F> {
         RunUntil {
             sim: self,
             t_final: t_final,
         }
     }
 
-    pub fn run_for(&mut self, time: f64) -> RunUntil {
+    pub fn run_for(&mut self, time: F) -> RunUntil<F> {
         let t = self.t;
         self.run_until(t + time)
     }
 
-    pub fn anneal(&mut self, time: f64, temperature: f64) {
+    pub fn anneal(&mut self, time: F, temperature: F) {
         // Reset phi (i.e. remove negative effects of irradiation)
-        self.phi = 0.0;
+        self.phi = F::zero();
         // Maybe change t1n?
-        self.t1n *= 0.8;
+        self.t1n = self.t1n * f(0.8);
 
         let temp_tmp = self.system_temperature;
         self.set_system_temperature(temperature);
@@ -218,7 +247,7 @@ impl Simulation {
         self.set_system_temperature(temp_tmp);
     }
 
-    pub fn take_data(&self) -> SimData {
+    pub fn take_data(&self) -> SimData<F> {
         SimData {
             time: self.t,
             pn: self.pn,
@@ -230,8 +259,8 @@ impl Simulation {
         }
     }
 
-    fn set_temperature(&mut self, temperature: f64) {
-        self.pe0 = -(2.0 / temperature).tanh();
+    fn set_temperature(&mut self, temperature: F) {
+        self.pe0 = -(f::<F>(2.0) / temperature).tanh();
         self.temperature = temperature;
     }
 
@@ -241,46 +270,47 @@ impl Simulation {
         // K_TEMP = rate of exponential increase
         // If we're annealing, we shouldn't allow the temperature to change
         // (assume anneals occur at constant temperature)
-        let k_temp = 0.01;
-        let temp_ss = self.system_temperature + self.beam_current / 100.0;
+        let k_temp: F = f(0.01);
+        let temp_ss = self.system_temperature + self.beam_current / f(100.0);
 
         // Increase phi according to some exponential growth when the beam is on
         // Parameters are similar to those for temperature change
-        let k_phi = self.beam_current / 1e7;
-        let phi_ss = 0.001;
+        let k_phi = self.beam_current / f(1e7);
+        let phi_ss: F = f(0.001);
+
+        // Shortcut calculation
+        let time_amt: F = f(TIME_STEP / N_ITER as f64);
 
         for _ in 0..N_ITER {
             // Calculate constants (for convenience)
-            let a_const = -self.t1e / self.t1n - (self.c / 2.0) * (self.alpha + self.beta) -
-                          self.phi;
-            let b_const = (self.c / 2.0) * (self.alpha - self.beta);
-            let c_const = (self.alpha - self.beta) / 2.0;
-            let d_const = -1.0 - (self.alpha + self.beta) / 2.0;
+            let a_const = -self.t1e / self.t1n -
+                          (self.c / f(2.0)) * (self.alpha + self.beta) - self.phi;
+            let b_const = (self.c / f(2.0)) * (self.alpha - self.beta);
+            let c_const = (self.alpha - self.beta) / f(2.0);
+            let d_const = -F::one() - (self.alpha + self.beta) / f(2.0);
 
             // Calculate rates
             let pn_prime = (a_const * self.pn_raw + b_const * self.pe) / self.t1e;
             let pe_prime = (c_const * self.pn_raw + d_const * self.pe + self.pe0) / self.t1e;
 
-            // Shortcut calculation
-            let time_amt = TIME_STEP / N_ITER as f64;
-
             // Update pn and pe (Euler's method)
-            self.pn_raw += pn_prime * time_amt;
-            self.pe += pe_prime * time_amt;
+            self.pn_raw = self.pn_raw + pn_prime * time_amt;
+            self.pe = self.pe + pe_prime * time_amt;
             // Update temperature and phi
             let temp = self.temperature;
             self.set_temperature(temp + time_amt * k_temp * (temp_ss - temp));
-            self.phi += time_amt * k_phi * (phi_ss - self.phi);
+            self.phi = self.phi + time_amt * k_phi * (phi_ss - self.phi);
 
             // Update C and dose
-            self.c += IRRADIATION_FACTOR * self.beam_current * time_amt;
-            self.dose += (self.beam_current * 1e-9 / ELEM_CHARGE) * time_amt;
+            self.c = self.c + f::<F>(IRRADIATION_FACTOR) * self.beam_current * time_amt;
+            self.dose = self.dose +
+                        (self.beam_current * f::<F>(1e-9) / f::<F>(ELEM_CHARGE)) * time_amt;
 
             // Calculate new transition rates (alpha and beta)
             self.calc_transition_rates();
 
             // Update time
-            self.t += time_amt;
+            self.t = self.t + time_amt;
         }
 
         // Update "noisy pn"
@@ -289,30 +319,32 @@ impl Simulation {
 
     fn calc_transition_rates(&mut self) {
         // Calculate distribution parameters (the means m1 and m2 are particularly important)
-        let fit_m1 = (FIT_M1_BASE - FIT_M1_COEFF) + FIT_M1_COEFF * (FIT_M1_RATE * self.dose).exp();
-        let fit_m2 = (FIT_M2_BASE - FIT_M2_COEFF) + FIT_M2_COEFF * (FIT_M2_RATE * self.dose).exp();
-        let scale = FIT_A / ((2.0 * consts::PI).sqrt() * FIT_S);
+        let fit_m1 = f::<F>(FIT_M1_BASE - FIT_M1_COEFF) +
+                     f::<F>(FIT_M1_COEFF) * (f::<F>(FIT_M1_RATE) * self.dose).exp();
+        let fit_m2 = f::<F>(FIT_M2_BASE - FIT_M2_COEFF) +
+                     f::<F>(FIT_M2_COEFF) * (f::<F>(FIT_M2_RATE) * self.dose).exp();
+        let scale = f::<F>(FIT_A) / ((f::<F>(2.0) * F::PI()).sqrt() * f::<F>(FIT_S));
         let diff1 = self.freq - fit_m1;
         let diff2 = self.freq - fit_m2;
-        let exp1 = (-diff1 * diff1 / (2.0 * FIT_S * FIT_S)).exp();
-        let exp2 = (-diff2 * diff2 / (2.0 * FIT_S * FIT_S)).exp();
+        let exp1 = (-diff1 * diff1 / (f::<F>(2.0) * f::<F>(FIT_S) * f::<F>(FIT_S))).exp();
+        let exp2 = (-diff2 * diff2 / (f::<F>(2.0) * f::<F>(FIT_S) * f::<F>(FIT_S))).exp();
 
         self.alpha = scale * exp2;
         self.beta = scale * exp1;
     }
 
-    fn pn_noisy(&mut self) -> f64 {
-        let thermal_noise = THERMAL_RANDOMNESS * (0.5 - rand::random::<f64>());
-        let uniform_noise = BASE_RANDOMNESS * (0.5 - rand::random::<f64>());
+    fn pn_noisy(&mut self) -> F {
+        let thermal_noise = f::<F>(THERMAL_RANDOMNESS) * (f::<F>(0.5) - f(rand::random::<f64>()));
+        let uniform_noise = f::<F>(BASE_RANDOMNESS) * (f::<F>(0.5) - f(rand::random::<f64>()));
 
-        self.pn_raw * (1.0 + thermal_noise) + uniform_noise
+        self.pn_raw * (F::one() + thermal_noise) + uniform_noise
     }
 }
 
-impl<'a> Iterator for RunUntil<'a> {
-    type Item = SimData;
+impl<'a, F: Flt> Iterator for RunUntil<'a, F> {
+    type Item = SimData<F>;
 
-    fn next(&mut self) -> Option<SimData> {
+    fn next(&mut self) -> Option<SimData<F>> {
         if self.sim.t < self.t_final {
             let data = self.sim.take_data();
             self.sim.time_step();